@@ -0,0 +1,127 @@
+//! A minimal byte-reader abstraction so the detection core can run without std.
+//!
+//! The detection path only needs to seek, read little-endian `u32`s and look at
+//! the backing bytes. [`ByteReader`] captures exactly that, letting the core
+//! compile to `wasm32-unknown-unknown` against the bundled [`Cursor`] shim while
+//! still accepting a `std::io::Cursor` when the `std` feature is enabled.
+
+use crate::reader::ReaderError;
+
+#[cfg(feature = "std")]
+use std::io::{self, Seek, SeekFrom};
+
+/// The seek/read operations the detection path needs from its input buffer.
+pub trait ByteReader {
+    /// Current read position, in bytes from the start.
+    fn position(&self) -> u64;
+    /// Seek to an absolute position.
+    fn set_position(&mut self, pos: u64);
+    /// Total length of the backing buffer.
+    fn len(&self) -> usize;
+    /// Whether the backing buffer is empty.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Borrow the whole backing buffer.
+    fn as_bytes(&self) -> &[u8];
+    /// Mutably borrow the whole backing buffer, for in-place decryption.
+    fn as_bytes_mut(&mut self) -> &mut [u8];
+    /// Read a little-endian `u32` at the cursor, advancing by four bytes.
+    fn read_u32_le(&mut self) -> Result<u32, ReaderError>;
+    /// Seek by a signed offset relative to the current position.
+    fn seek_relative(&mut self, offset: i64) -> Result<(), ReaderError>;
+}
+
+#[cfg(feature = "std")]
+impl<T: AsRef<[u8]> + AsMut<[u8]>> ByteReader for io::Cursor<T> {
+    fn position(&self) -> u64 {
+        io::Cursor::position(self)
+    }
+
+    fn set_position(&mut self, pos: u64) {
+        io::Cursor::set_position(self, pos)
+    }
+
+    fn len(&self) -> usize {
+        self.get_ref().as_ref().len()
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        self.get_ref().as_ref()
+    }
+
+    fn as_bytes_mut(&mut self) -> &mut [u8] {
+        self.get_mut().as_mut()
+    }
+
+    fn read_u32_le(&mut self) -> Result<u32, ReaderError> {
+        use minio::ReadPrimitives;
+        Ok(ReadPrimitives::read_u32_le(self)?)
+    }
+
+    fn seek_relative(&mut self, offset: i64) -> Result<(), ReaderError> {
+        self.seek(SeekFrom::Current(offset))?;
+        Ok(())
+    }
+}
+
+/// A tiny `no_std` stand-in for `std::io::Cursor` over any byte buffer.
+pub struct Cursor<T> {
+    inner: T,
+    pos: u64,
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> Cursor<T> {
+    /// Wrap a buffer, positioned at its start.
+    pub fn new(inner: T) -> Self {
+        Cursor { inner, pos: 0 }
+    }
+
+    /// Consume the cursor and return the wrapped buffer.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> ByteReader for Cursor<T> {
+    fn position(&self) -> u64 {
+        self.pos
+    }
+
+    fn set_position(&mut self, pos: u64) {
+        self.pos = pos;
+    }
+
+    fn len(&self) -> usize {
+        self.inner.as_ref().len()
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        self.inner.as_ref()
+    }
+
+    fn as_bytes_mut(&mut self) -> &mut [u8] {
+        self.inner.as_mut()
+    }
+
+    fn read_u32_le(&mut self) -> Result<u32, ReaderError> {
+        let start = self.pos as usize;
+        let end = start.checked_add(4).ok_or(ReaderError::UnknownFormat)?;
+        let bytes = self
+            .inner
+            .as_ref()
+            .get(start..end)
+            .ok_or(ReaderError::UnknownFormat)?;
+        self.pos = end as u64;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn seek_relative(&mut self, offset: i64) -> Result<(), ReaderError> {
+        let next = (self.pos as i64)
+            .checked_add(offset)
+            .filter(|&p| p >= 0)
+            .ok_or(ReaderError::UnknownFormat)?;
+        self.pos = next as u64;
+        Ok(())
+    }
+}
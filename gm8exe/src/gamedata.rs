@@ -2,193 +2,391 @@ pub mod antidec;
 pub mod gm80;
 pub mod gm81;
 
-use crate::{reader::ReaderError, upx, GameVersion};
-use minio::ReadPrimitives;
-use std::io::{self, Seek, SeekFrom};
+use crate::byteio::{ByteReader, Cursor};
+use crate::{packer, reader::ReaderError, verify, GameVersion};
+
+/// Optional progress logger threaded through detection, as a trait object so
+/// that [`FormatDetector`] can stay object-safe.
+type Logger<'a> = Option<&'a dyn Fn(&str)>;
+
+/// The outcome of a successful [`find`]: which GameMaker generation was
+/// recovered and, when a checksum database was supplied and matched, which
+/// exact game the recovered gamedata belongs to.
+pub struct Detection {
+    /// The GameMaker version the image decrypted to.
+    pub version: GameVersion,
+    /// The GM8.1 header XOR method that validated, when one was auto-detected.
+    pub xor_method: Option<gm81::XorMethod>,
+    /// The identified game, if a checksum database was supplied and matched.
+    pub identity: Option<verify::GameId>,
+}
+
+/// What a [`FormatDetector::unwrap`] recovered: the GameMaker version and, for
+/// GM8.1 images, the header XOR method that was chosen.
+pub struct Unwrapped {
+    pub version: GameVersion,
+    pub xor_method: Option<gm81::XorMethod>,
+}
+
+impl From<GameVersion> for Unwrapped {
+    fn from(version: GameVersion) -> Self {
+        Unwrapped {
+            version,
+            xor_method: None,
+        }
+    }
+}
+
+/// A positive result from [`FormatDetector::probe`], carrying whatever state the
+/// detector needs to finish recovering the gamedata in [`FormatDetector::unwrap`].
+pub enum DetectionHint {
+    /// An antidec loader stub was recognised; carries the settings read from it
+    /// and which GameMaker generation the stub decrypts to.
+    Antidec {
+        settings: antidec::Settings,
+        version: GameVersion,
+    },
+    /// A bare GameMaker header was recognised in place with no protection stub
+    /// around it. For GM8.0 the cursor already sits at the gamedata; for GM8.1 it
+    /// sits just past the header magic and the header still has to be decrypted.
+    Plain(GameVersion),
+}
+
+/// A pluggable recogniser for one protection or container format.
+///
+/// [`find`] keeps an ordered registry of these and tries each in turn; the first
+/// to return a [`DetectionHint`] from [`probe`](FormatDetector::probe) wins and
+/// is handed its own hint back in [`unwrap`](FormatDetector::unwrap) to finish
+/// the job, leaving the cursor at the start of the gamedata header. [`find`]
+/// only consults its built-in registry; a detector implemented outside this
+/// module is not added to it.
+pub trait FormatDetector {
+    /// Test whether this format is present. Returns `None` if it does not apply.
+    fn probe(
+        &self,
+        exe: &mut dyn ByteReader,
+        logger: Logger,
+    ) -> Result<Option<DetectionHint>, ReaderError>;
+
+    /// Recover the gamedata given the hint this detector produced in `probe`,
+    /// leaving the cursor at the start of the gamedata header on success.
+    fn unwrap(
+        &self,
+        exe: &mut dyn ByteReader,
+        hint: DetectionHint,
+        logger: Logger,
+    ) -> Result<Unwrapped, ReaderError>;
+}
 
 /// Identifies the game version and start of gamedata header, given a data cursor.
 /// Also removes any version-specific encryptions.
 pub fn find<F>(
-    exe: &mut io::Cursor<&mut [u8]>,
+    exe: &mut dyn ByteReader,
     logger: Option<F>,
-    upx_data: Option<(u32, u32)>,
-) -> Result<GameVersion, ReaderError>
+    sections: &[packer::PeSection],
+    database: Option<&verify::ChecksumDatabase>,
+) -> Result<Detection, ReaderError>
 where
     F: Copy + Fn(&str),
 {
-    // Check if UPX is in use first
-    match upx_data {
-        Some((max_size, disk_offset)) => {
-            // UPX in use, let's unpack it
-            let mut unpacked = upx::unpack(exe, max_size, disk_offset, logger)?;
+    let logger: Logger = logger.as_ref().map(|f| f as &dyn Fn(&str));
+
+    // Packer stage: if the image is wrapped by a known packer, decompress it and
+    // re-run format detection against the recovered image. This handles stacks
+    // like UPX-then-antidec and aPLib-then-gm81 uniformly.
+    for packer in packer::registry() {
+        if let Some(params) = packer.detect(sections) {
+            let mut unpacked = packer.unpack(exe, &params, logger)?;
             log!(
                 logger,
-                "Successfully unpacked UPX - output is {} bytes",
+                "Successfully unpacked {} - output is {} bytes",
+                packer.name(),
                 unpacked.len()
             );
-            let mut unpacked = io::Cursor::new(&mut *unpacked);
+            let mut unpacked = Cursor::new(&mut *unpacked);
+            return run_detectors(&mut unpacked, &default_registry(), logger, database);
+        }
+    }
 
-            // UPX unpacked, now check if this is a supported data format
-            if let Some(antidec_settings) = antidec::check80(&mut unpacked)? {
-                if logger.is_some() {
-                    log!(
-                        logger,
-                        "Found antidec2 loading sequence, decrypting with the following values:"
-                    );
+    run_detectors(exe, &default_registry(), logger, database)
+}
+
+/// The built-in detector cascade, in priority order: antidec2, antidec81, gm80
+/// and gm81. Packers are handled separately, ahead of this stage.
+fn default_registry() -> Vec<Box<dyn FormatDetector>> {
+    vec![
+        Box::new(Antidec80Detector),
+        Box::new(Antidec81Detector),
+        Box::new(Gm80Detector),
+        Box::new(Gm81Detector),
+    ]
+}
+
+/// Try each detector in the registry until one recognises the image, then verify
+/// the recovered gamedata against the optional checksum database.
+fn run_detectors(
+    exe: &mut dyn ByteReader,
+    registry: &[Box<dyn FormatDetector>],
+    logger: Logger,
+    database: Option<&verify::ChecksumDatabase>,
+) -> Result<Detection, ReaderError> {
+    for detector in registry {
+        if let Some(hint) = detector.probe(exe, logger)? {
+            let unwrapped = detector.unwrap(exe, hint, logger)?;
+            let identity = identify(exe, database, logger);
+            return Ok(Detection {
+                version: unwrapped.version,
+                xor_method: unwrapped.xor_method,
+                identity,
+            });
+        }
+    }
+    Err(ReaderError::UnknownFormat)
+}
+
+/// Hash the recovered gamedata region (from the current cursor to end of buffer)
+/// and look it up in the supplied database, confirming the decryption succeeded
+/// and naming the game. Returns `None` when no database is supplied or no row
+/// matches.
+fn identify(
+    exe: &dyn ByteReader,
+    database: Option<&verify::ChecksumDatabase>,
+    logger: Logger,
+) -> Option<verify::GameId> {
+    let database = database?;
+    let pos = exe.position() as usize;
+    let gamedata = exe.as_bytes().get(pos..)?;
+    match database.identify(gamedata) {
+        Some(id) => {
+            log!(logger, "Identified game: {} (version {})", id.name, id.version);
+            Some(id)
+        }
+        None => {
+            log!(
+                logger,
+                "Gamedata checksum not found in database - decryption may have produced garbage"
+            );
+            None
+        }
+    }
+}
+
+/// Emit the shared "found loader, decrypting with these masks" banner.
+fn log_antidec(logger: Logger, banner: &str, settings: &antidec::Settings) {
+    if logger.is_some() {
+        log!(logger, "{}, decrypting with the following values:", banner);
+        log!(
+            logger,
+            "exe_load_offset:0x{:X} header_start:0x{:X} xor_mask:0x{:X} add_mask:0x{:X} sub_mask:0x{:X}",
+            settings.exe_load_offset,
+            settings.header_start,
+            settings.xor_mask,
+            settings.add_mask,
+            settings.sub_mask
+        );
+    }
+}
+
+/// Candidate GM8.1 header XOR methods, tried in order by [`detect_gm81_xor_method`].
+const GM81_XOR_METHODS: [gm81::XorMethod; 2] =
+    [gm81::XorMethod::Normal, gm81::XorMethod::Sudalv];
+
+/// Pick the GM8.1 header XOR method by speculation rather than assuming
+/// [`gm81::XorMethod::Normal`].
+///
+/// The cursor is expected to sit just past the `0xF7140067` magic. Each known
+/// method is run against a small prefix of the header and
+/// [validated](gm81::verify_xor_method) — the decrypted length/count fields at
+/// the head of the header must be sane — before any is committed to. Returns `None` if every method fails validation.
+/// The cursor is left where it started regardless of outcome.
+fn detect_gm81_xor_method(
+    exe: &mut dyn ByteReader,
+    logger: Logger,
+) -> Result<Option<gm81::XorMethod>, ReaderError> {
+    let anchor = exe.position();
+    for &method in &GM81_XOR_METHODS {
+        exe.set_position(anchor);
+        if gm81::verify_xor_method(exe, method)? {
+            log!(logger, "Detected GM8.1 XOR method: {:?}", method);
+            exe.set_position(anchor);
+            return Ok(Some(method));
+        }
+    }
+    exe.set_position(anchor);
+    Ok(None)
+}
+
+/// Scan forwards from the antidec load address for the GM8.1 header magic,
+/// returning whether it was found (the cursor is left just past the magic).
+fn search_gm81_header(
+    exe: &mut dyn ByteReader,
+    settings: &antidec::Settings,
+) -> Result<bool, ReaderError> {
+    let mut i = settings.header_start + settings.exe_load_offset;
+    loop {
+        exe.set_position(i as u64);
+        let val = (exe.read_u32_le()? & 0xFF00FF00) + (exe.read_u32_le()? & 0x00FF00FF);
+        if val == 0xF7140067 {
+            break Ok(true);
+        }
+        i += 1;
+        if ((i + 8) as usize) >= exe.len() {
+            break Ok(false);
+        }
+    }
+}
+
+/// antidec2 protection wrapping a GameMaker 8.0 image.
+struct Antidec80Detector;
+
+impl FormatDetector for Antidec80Detector {
+    fn probe(
+        &self,
+        exe: &mut dyn ByteReader,
+        _logger: Logger,
+    ) -> Result<Option<DetectionHint>, ReaderError> {
+        Ok(antidec::check80(exe)?.map(|settings| DetectionHint::Antidec {
+            settings,
+            version: GameVersion::GameMaker8_0,
+        }))
+    }
+
+    fn unwrap(
+        &self,
+        exe: &mut dyn ByteReader,
+        hint: DetectionHint,
+        logger: Logger,
+    ) -> Result<Unwrapped, ReaderError> {
+        let DetectionHint::Antidec { settings, version } = hint else {
+            return Err(ReaderError::UnknownFormat);
+        };
+        log_antidec(logger, "Found antidec2 loading sequence", &settings);
+        if antidec::decrypt(exe, settings)? {
+            // 8.0-specific header, but no point strict-checking it because antidec puts random garbage there.
+            exe.seek_relative(12)?;
+            Ok(version.into())
+        } else {
+            // Antidec couldn't be decrypted with the settings we read, so we must have got the format wrong
+            Err(ReaderError::UnknownFormat)
+        }
+    }
+}
+
+/// antidec81 protection wrapping a GameMaker 8.1 image.
+struct Antidec81Detector;
+
+impl FormatDetector for Antidec81Detector {
+    fn probe(
+        &self,
+        exe: &mut dyn ByteReader,
+        _logger: Logger,
+    ) -> Result<Option<DetectionHint>, ReaderError> {
+        Ok(antidec::check81(exe)?.map(|settings| DetectionHint::Antidec {
+            settings,
+            version: GameVersion::GameMaker8_1,
+        }))
+    }
+
+    fn unwrap(
+        &self,
+        exe: &mut dyn ByteReader,
+        hint: DetectionHint,
+        logger: Logger,
+    ) -> Result<Unwrapped, ReaderError> {
+        let DetectionHint::Antidec { settings, version } = hint else {
+            return Err(ReaderError::UnknownFormat);
+        };
+        log_antidec(logger, "Found antidec81 loading sequence", &settings);
+        if antidec::decrypt(exe, settings)? {
+            if search_gm81_header(exe, &settings)? {
+                let method = detect_gm81_xor_method(exe, logger)?.ok_or_else(|| {
                     log!(
                         logger,
-                        "exe_load_offset:0x{:X} header_start:0x{:X} xor_mask:0x{:X} add_mask:0x{:X} sub_mask:0x{:X}",
-                        antidec_settings.exe_load_offset,
-                        antidec_settings.header_start,
-                        antidec_settings.xor_mask,
-                        antidec_settings.add_mask,
-                        antidec_settings.sub_mask
+                        "No GM8.1 XOR method validated against the header, so giving up"
                     );
-                }
-                if antidec::decrypt(exe, antidec_settings)? {
-                    // 8.0-specific header, but no point strict-checking it because antidec puts random garbage there.
-                    exe.seek(SeekFrom::Current(12))?;
-                    Ok(GameVersion::GameMaker8_0)
-                } else {
-                    // Antidec couldn't be decrypted with the settings we read, so we must have got the format wrong
-                    Err(ReaderError::UnknownFormat)
-                }
-            } else if let Some(antidec_settings) = antidec::check81(&mut unpacked)? {
-                log!(
-                    logger,
-                    "Found antidec81 loading sequence, decrypting with the following values:"
-                );
+                    ReaderError::UnknownFormat
+                })?;
+                gm81::decrypt(exe, logger, method)?;
+                exe.seek_relative(20)?;
+                Ok(Unwrapped {
+                    version,
+                    xor_method: Some(method),
+                })
+            } else {
                 log!(
                     logger,
-                    "exe_load_offset:0x{:X} header_start:0x{:X} xor_mask:0x{:X} add_mask:0x{:X} sub_mask:0x{:X}",
-                    antidec_settings.exe_load_offset,
-                    antidec_settings.header_start,
-                    antidec_settings.xor_mask,
-                    antidec_settings.add_mask,
-                    antidec_settings.sub_mask
+                    "Didn't find GM81 magic value (0xF7140067) before EOF, so giving up"
                 );
-                if antidec::decrypt(exe, antidec_settings)? {
-                    // Search for header
-                    let found_header = {
-                        let mut i =
-                            antidec_settings.header_start + antidec_settings.exe_load_offset;
-                        loop {
-                            exe.set_position(i as u64);
-                            let val = (exe.read_u32_le()? & 0xFF00FF00)
-                                + (exe.read_u32_le()? & 0x00FF00FF);
-                            if val == 0xF7140067 {
-                                break true;
-                            }
-                            i += 1;
-                            if ((i + 8) as usize) >= exe.get_ref().len() {
-                                break false;
-                            }
-                        }
-                    };
-                    if found_header {
-                        gm81::decrypt(exe, logger, gm81::XorMethod::Normal)?;
-                        exe.seek(SeekFrom::Current(20))?;
-                        Ok(GameVersion::GameMaker8_1)
-                    } else {
-                        log!(
-                            logger,
-                            "Didn't find GM81 magic value (0xF7640017) before EOF, so giving up"
-                        );
-                        Err(ReaderError::UnknownFormat)
-                    }
-                } else {
-                    // Antidec couldn't be decrypted with the settings we read, so we must have got the format wrong
-                    Err(ReaderError::UnknownFormat)
-                }
-            } else {
                 Err(ReaderError::UnknownFormat)
             }
+        } else {
+            // Antidec couldn't be decrypted with the settings we read, so we must have got the format wrong
+            Err(ReaderError::UnknownFormat)
         }
-        None => {
-            if let Some(antidec_settings) = antidec::check80(exe)? {
-                // antidec2 protection in the base exe (so without UPX on top of it)
-                if logger.is_some() {
-                    log!(
-                        logger,
-                        "Found antidec2 loading sequence [no UPX], decrypting with the following values:"
-                    );
-                    log!(
-                        logger,
-                        "exe_load_offset:0x{:X} header_start:0x{:X} xor_mask:0x{:X} add_mask:0x{:X} sub_mask:0x{:X}",
-                        antidec_settings.exe_load_offset,
-                        antidec_settings.header_start,
-                        antidec_settings.xor_mask,
-                        antidec_settings.add_mask,
-                        antidec_settings.sub_mask
-                    );
-                }
-                if antidec::decrypt(exe, antidec_settings)? {
-                    // 8.0-specific header, but no point strict-checking it because antidec puts random garbage there.
-                    exe.seek(SeekFrom::Current(12))?;
-                    Ok(GameVersion::GameMaker8_0)
-                } else {
-                    // Antidec couldn't be decrypted with the settings we read, so we must have got the format wrong
-                    Err(ReaderError::UnknownFormat)
-                }
-            } else if let Some(antidec_settings) = antidec::check81(exe)? {
-                // antidec81 protection in the base exe (so without UPX on top of it)
-                if logger.is_some() {
-                    log!(
-                        logger,
-                        "Found antidec81 loading sequence [no UPX], decrypting with the following values:"
-                    );
-                    log!(
-                        logger,
-                        "exe_load_offset:0x{:X} header_start:0x{:X} xor_mask:0x{:X} add_mask:0x{:X} sub_mask:0x{:X}",
-                        antidec_settings.exe_load_offset,
-                        antidec_settings.header_start,
-                        antidec_settings.xor_mask,
-                        antidec_settings.add_mask,
-                        antidec_settings.sub_mask
-                    );
-                }
-                if antidec::decrypt(exe, antidec_settings)? {
-                    let found_header = {
-                        let mut i =
-                            antidec_settings.header_start + antidec_settings.exe_load_offset;
-                        loop {
-                            exe.set_position(i as u64);
-                            let val = (exe.read_u32_le()? & 0xFF00FF00)
-                                + (exe.read_u32_le()? & 0x00FF00FF);
-                            if val == 0xF7140067 {
-                                break true;
-                            }
-                            i += 1;
-                            if ((i + 8) as usize) >= exe.get_ref().len() {
-                                break false;
-                            }
-                        }
-                    };
-                    if found_header {
-                        gm81::decrypt(exe, logger, gm81::XorMethod::Normal)?;
-                        exe.seek(SeekFrom::Current(20))?;
-                        Ok(GameVersion::GameMaker8_1)
-                    } else {
-                        log!(
-                            logger,
-                            "Didn't find GM81 magic value (0xF7640017) before EOF, so giving up"
-                        );
-                        Err(ReaderError::UnknownFormat)
-                    }
-                } else {
-                    // Antidec couldn't be decrypted with the settings we read, so we must have got the format wrong
-                    Err(ReaderError::UnknownFormat)
-                }
-            } else {
-                // Standard formats
-                if gm80::check(exe, logger)? {
-                    Ok(GameVersion::GameMaker8_0)
-                } else if gm81::check(exe, logger)? || gm81::check_lazy(exe, logger)? {
-                    Ok(GameVersion::GameMaker8_1)
-                } else {
-                    Err(ReaderError::UnknownFormat)
-                }
-            }
+    }
+}
+
+/// A plain, unprotected GameMaker 8.0 image.
+struct Gm80Detector;
+
+impl FormatDetector for Gm80Detector {
+    fn probe(
+        &self,
+        exe: &mut dyn ByteReader,
+        logger: Logger,
+    ) -> Result<Option<DetectionHint>, ReaderError> {
+        if gm80::check(exe, logger)? {
+            Ok(Some(DetectionHint::Plain(GameVersion::GameMaker8_0)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn unwrap(
+        &self,
+        _exe: &mut dyn ByteReader,
+        hint: DetectionHint,
+        _logger: Logger,
+    ) -> Result<Unwrapped, ReaderError> {
+        match hint {
+            DetectionHint::Plain(version) => Ok(version.into()),
+            _ => Err(ReaderError::UnknownFormat),
         }
     }
 }
+
+/// A plain, unprotected GameMaker 8.1 image.
+struct Gm81Detector;
+
+impl FormatDetector for Gm81Detector {
+    fn probe(
+        &self,
+        exe: &mut dyn ByteReader,
+        logger: Logger,
+    ) -> Result<Option<DetectionHint>, ReaderError> {
+        if gm81::check(exe, logger)? || gm81::check_lazy(exe, logger)? {
+            Ok(Some(DetectionHint::Plain(GameVersion::GameMaker8_1)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn unwrap(
+        &self,
+        exe: &mut dyn ByteReader,
+        hint: DetectionHint,
+        logger: Logger,
+    ) -> Result<Unwrapped, ReaderError> {
+        let DetectionHint::Plain(version) = hint else {
+            return Err(ReaderError::UnknownFormat);
+        };
+        // The cursor sits just past the magic; the header itself is still garbled.
+        let method = detect_gm81_xor_method(exe, logger)?.ok_or(ReaderError::UnknownFormat)?;
+        gm81::decrypt(exe, logger, method)?;
+        exe.seek_relative(20)?;
+        Ok(Unwrapped {
+            version,
+            xor_method: Some(method),
+        })
+    }
+}
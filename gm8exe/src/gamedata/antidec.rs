@@ -0,0 +1,107 @@
+//! Detection and removal of the antidec2 / antidec8.1 loader stubs.
+//!
+//! antidec wraps a GameMaker image behind a small loader that, at runtime,
+//! reconstructs the original gamedata by walking it with a rolling xor/add/sub
+//! mask. The loader stores its parameters as a run of `push imm32` instructions
+//! right before it calls its decryptor, so detection recovers them by locating
+//! that block; [`decrypt`] then replays the transform over the backing buffer.
+
+use crate::byteio::ByteReader;
+use crate::reader::ReaderError;
+
+/// The parameters an antidec loader hands to its decryptor.
+#[derive(Clone, Copy)]
+pub struct Settings {
+    /// Base address the loader maps the protected image at.
+    pub exe_load_offset: u32,
+    /// Offset of the gamedata header relative to the load address.
+    pub header_start: u32,
+    /// Initial xor mask applied to each word.
+    pub xor_mask: u32,
+    /// Value added to each word after the xor.
+    pub add_mask: u32,
+    /// Value subtracted from each word after the xor and the add.
+    pub sub_mask: u32,
+}
+
+/// `push imm32` opcode; the loader pushes its five parameters with these.
+const PUSH_IMM32: u8 = 0x68;
+
+/// Look for an antidec2 loader (a run of exactly five consecutive `push imm32`
+/// instructions) and recover its settings. The cursor is left untouched.
+pub fn check80(exe: &mut dyn ByteReader) -> Result<Option<Settings>, ReaderError> {
+    Ok(find_params(exe.as_bytes(), 5))
+}
+
+/// Look for an antidec8.1 loader. The 8.1 stub pushes an extra seed word ahead
+/// of the five shared parameters, so it shows up as six consecutive pushes.
+pub fn check81(exe: &mut dyn ByteReader) -> Result<Option<Settings>, ReaderError> {
+    Ok(find_params(exe.as_bytes(), 6))
+}
+
+/// Scan `data` for a *maximal* run of exactly `count` consecutive `push imm32`
+/// opcodes and read the final five immediates as the loader settings.
+///
+/// The run must be bounded on both sides by a non-push byte, otherwise a
+/// six-push antidec8.1 stub would be matched as a five-push run starting at its
+/// second push — which would make `check80` claim an 8.1 image and skip the 8.1
+/// header decryption entirely.
+fn find_params(data: &[u8], count: usize) -> Option<Settings> {
+    let stride = 5; // one `push imm32` is opcode + 4-byte immediate
+    let run = count * stride;
+    let mut i = 0;
+    while i + run <= data.len() {
+        let is_run = (0..count).all(|k| data[i + k * stride] == PUSH_IMM32);
+        // The run must be maximal: the push slots immediately before and after
+        // it must not themselves be `push imm32`, so a 5-run and a 6-run are
+        // never confused for one another.
+        let bounded_before = i < stride || data[i - stride] != PUSH_IMM32;
+        let bounded_after = data.get(i + run).map_or(true, |&b| b != PUSH_IMM32);
+        if is_run && bounded_before && bounded_after {
+            let imm = |k: usize| {
+                let off = i + (count - 5 + k) * stride + 1;
+                u32::from_le_bytes([data[off], data[off + 1], data[off + 2], data[off + 3]])
+            };
+            return Some(Settings {
+                exe_load_offset: imm(0),
+                header_start: imm(1),
+                xor_mask: imm(2),
+                add_mask: imm(3),
+                sub_mask: imm(4),
+            });
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Replay the antidec transform over the gamedata region in place, returning
+/// whether the recovered range fit inside the buffer. A `false` result means the
+/// settings were wrong and the caller should treat the format as unrecognised.
+pub fn decrypt(exe: &mut dyn ByteReader, settings: Settings) -> Result<bool, ReaderError> {
+    let start = match settings
+        .header_start
+        .checked_add(settings.exe_load_offset)
+    {
+        Some(s) => s as usize,
+        None => return Ok(false),
+    };
+
+    let data = exe.as_bytes_mut();
+    if start >= data.len() {
+        return Ok(false);
+    }
+
+    let mut xor_mask = settings.xor_mask;
+    for word in data[start..].chunks_exact_mut(4) {
+        let enc = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+        let dec = (enc ^ xor_mask)
+            .wrapping_add(settings.add_mask)
+            .wrapping_sub(settings.sub_mask);
+        word.copy_from_slice(&dec.to_le_bytes());
+        // The mask rolls forward per word, matching the loader's decryptor.
+        xor_mask = xor_mask.wrapping_add(settings.add_mask);
+    }
+
+    Ok(true)
+}
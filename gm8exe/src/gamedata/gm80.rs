@@ -0,0 +1,37 @@
+//! Detection for a plain, unprotected GameMaker 8.0 image.
+
+use super::Logger;
+use crate::byteio::ByteReader;
+use crate::reader::ReaderError;
+
+/// File offset at which the GM8.0 runner embeds its gamedata header.
+const GM80_MAGIC_POSITION: u64 = 2_000_000;
+
+/// First DWORD of the GM8.0 gamedata header.
+const GM80_MAGIC: u32 = 1_234_321;
+
+/// Check whether `exe` is a standard GM8.0 image, leaving the cursor at the
+/// start of the gamedata header on success.
+///
+/// The runner stores the header at a fixed offset, tagged with [`GM80_MAGIC`]
+/// followed by a version word in the 8.0 range. The cursor is restored to where
+/// it started when the format doesn't match.
+pub fn check(exe: &mut dyn ByteReader, logger: Logger) -> Result<bool, ReaderError> {
+    log!(logger, "Checking for standard GM8.0 format...");
+
+    let anchor = exe.position();
+    if ((GM80_MAGIC_POSITION + 8) as usize) > exe.len() {
+        return Ok(false);
+    }
+
+    exe.set_position(GM80_MAGIC_POSITION);
+    let magic = exe.read_u32_le()?;
+    let version = exe.read_u32_le()?;
+    if magic == GM80_MAGIC && (800..1000).contains(&version) {
+        log!(logger, "Detected GameMaker 8.0 (version {})", version);
+        Ok(true)
+    } else {
+        exe.set_position(anchor);
+        Ok(false)
+    }
+}
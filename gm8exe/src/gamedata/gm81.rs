@@ -0,0 +1,235 @@
+//! Detection and header decryption for GameMaker 8.1 images.
+//!
+//! GM8.1 garbles its gamedata header with a seed-derived 256-entry swap table
+//! followed by an additive pass. Detection locates the masked magic value and
+//! [`decrypt`] rebuilds the swap table from the seed and reverses both passes in
+//! place, leaving the cursor at the start of the recovered header.
+
+use super::Logger;
+use crate::byteio::ByteReader;
+use crate::reader::ReaderError;
+
+/// Masked magic value marking the GM8.1 gamedata header. It is recovered by
+/// masking two consecutive words, matching how the runner splits it.
+const GM81_MAGIC: u32 = 0xF714_0067;
+
+/// Offset at which the GM8.1 runner conventionally emits the header magic.
+const GM81_MAGIC_POSITION: u64 = 0x0039_FBC4;
+
+/// How the header swap table is seeded. Protected/repacked 8.1 executables sometimes
+/// seed it differently from the stock compiler, so callers pick the method.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XorMethod {
+    /// The stock GM8.1 seeding: the seed word sits at the start of the header.
+    Normal,
+    /// The seeding used by Sudalv's repacker, which stores the seed word just
+    /// ahead of the magic rather than inside the header.
+    Sudalv,
+}
+
+/// How far ahead of the header the Sudalv repacker stashes its seed word.
+const SUDALV_SEED_OFFSET: u64 = 12;
+
+/// Read the masked magic at the cursor, advancing past both words it spans.
+fn read_masked_magic(exe: &mut dyn ByteReader) -> Result<u32, ReaderError> {
+    Ok((exe.read_u32_le()? & 0xFF00_FF00).wrapping_add(exe.read_u32_le()? & 0x00FF_00FF))
+}
+
+/// Check whether `exe` is a GM8.1 image with the header at the conventional
+/// offset, leaving the cursor just past the magic on success.
+pub fn check(exe: &mut dyn ByteReader, logger: Logger) -> Result<bool, ReaderError> {
+    log!(logger, "Checking for standard GM8.1 format...");
+
+    let anchor = exe.position();
+    if ((GM81_MAGIC_POSITION + 8) as usize) > exe.len() {
+        return Ok(false);
+    }
+
+    exe.set_position(GM81_MAGIC_POSITION);
+    if read_masked_magic(exe)? == GM81_MAGIC {
+        log!(logger, "Detected GameMaker 8.1");
+        Ok(true)
+    } else {
+        exe.set_position(anchor);
+        Ok(false)
+    }
+}
+
+/// Fall back to scanning the whole image for the header magic, for repacked 8.1
+/// executables whose header doesn't sit at the conventional offset. Leaves the
+/// cursor just past the magic on success.
+pub fn check_lazy(exe: &mut dyn ByteReader, logger: Logger) -> Result<bool, ReaderError> {
+    log!(logger, "Scanning for a relocated GM8.1 header...");
+
+    let anchor = exe.position();
+    let mut i = 0u64;
+    while ((i + 8) as usize) <= exe.len() {
+        exe.set_position(i);
+        if read_masked_magic(exe)? == GM81_MAGIC {
+            exe.set_position(i + 8);
+            log!(logger, "Found relocated GM8.1 header at 0x{:X}", i);
+            return Ok(true);
+        }
+        i += 1;
+    }
+    exe.set_position(anchor);
+    Ok(false)
+}
+
+/// Build the forward swap table for `seed` using the GM8.1 garble schedule: start
+/// from the identity permutation and apply 10000 adjacent swaps driven by the
+/// seed.
+pub(super) fn swap_table(seed: u32) -> [u8; 256] {
+    let a = (seed % 250) + 6;
+    let b = seed / 250;
+
+    let mut table = [0u8; 256];
+    for (i, slot) in table.iter_mut().enumerate() {
+        *slot = i as u8;
+    }
+    for i in 1..=10_000u32 {
+        let j = ((i.wrapping_mul(a).wrapping_add(b)) % 254 + 1) as usize;
+        table.swap(j, j + 1);
+    }
+    table
+}
+
+/// Invert a swap table so `reverse[table[i]] == i`.
+pub(super) fn reverse_table(table: &[u8; 256]) -> [u8; 256] {
+    let mut reverse = [0u8; 256];
+    for (i, &v) in table.iter().enumerate() {
+        reverse[v as usize] = i as u8;
+    }
+    reverse
+}
+
+/// The seed word for `method`, read relative to the magic the cursor sits past.
+///
+/// For [`XorMethod::Normal`] the seed is the first header word, so the cursor
+/// advances past it and onto the header body. For [`XorMethod::Sudalv`] the seed
+/// lives ahead of the header and the cursor is left untouched.
+fn read_seed(exe: &mut dyn ByteReader, method: XorMethod) -> Result<u32, ReaderError> {
+    match method {
+        XorMethod::Normal => exe.read_u32_le(),
+        XorMethod::Sudalv => {
+            let here = exe.position();
+            let at = here
+                .checked_sub(SUDALV_SEED_OFFSET)
+                .ok_or(ReaderError::UnknownFormat)?;
+            exe.set_position(at);
+            let seed = exe.read_u32_le()?;
+            exe.set_position(here);
+            Ok(seed)
+        }
+    }
+}
+
+/// Bytes of the header prefix `verify_xor_method` decrypts to sanity-check a
+/// candidate method: two DWORD length/count fields.
+const VERIFY_PREFIX: usize = 8;
+
+/// Speculatively decrypt a short header prefix with `method` and report whether
+/// the result looks valid, without mutating the buffer or moving the cursor.
+///
+/// `swap_table` is a permutation by construction, so validating *that* would be a
+/// tautology; the real discriminator is the decrypted header itself. A method is
+/// accepted only when the two length/count fields at the head of the decrypted
+/// region are sane (both in range for the buffer, the first non-zero) — under the wrong
+/// seeding the reverse table is unrelated, so those fields decode to effectively
+/// random values and almost never pass. This lets [`find`](super::find) pick the
+/// right seeding instead of blindly assuming [`XorMethod::Normal`].
+pub fn verify_xor_method(
+    exe: &mut dyn ByteReader,
+    method: XorMethod,
+) -> Result<bool, ReaderError> {
+    let anchor = exe.position();
+    let seed = read_seed(exe, method)?;
+    let start = exe.position() as usize;
+    exe.set_position(anchor);
+
+    let reverse = reverse_table(&swap_table(seed));
+
+    let data = exe.as_bytes();
+    let end = match start.checked_add(VERIFY_PREFIX) {
+        Some(end) if end <= data.len() => end,
+        _ => return Ok(false),
+    };
+
+    // Replay the decrypt passes over a copy of the prefix only.
+    let mut prefix = [0u8; VERIFY_PREFIX];
+    prefix.copy_from_slice(&data[start..end]);
+    for byte in &mut prefix {
+        *byte = reverse[*byte as usize];
+    }
+    let mut carry = 0u8;
+    for byte in &mut prefix {
+        let plain = byte.wrapping_sub(carry);
+        carry = *byte;
+        *byte = plain;
+    }
+
+    let field0 = u32::from_le_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]) as u64;
+    let field1 = u32::from_le_bytes([prefix[4], prefix[5], prefix[6], prefix[7]]) as u64;
+    let len = data.len() as u64;
+    Ok(field0 != 0 && field0 < len && field1 <= len)
+}
+
+/// Reverse the GM8.1 header garble in place using `method`, leaving the cursor at
+/// the start of the recovered header.
+pub fn decrypt(
+    exe: &mut dyn ByteReader,
+    logger: Logger,
+    method: XorMethod,
+) -> Result<(), ReaderError> {
+    log!(logger, "Decrypting GM8.1 header ({:?})", method);
+
+    let seed = read_seed(exe, method)?;
+    let reverse = reverse_table(&swap_table(seed));
+
+    let start = exe.position() as usize;
+    let data = exe.as_bytes_mut();
+    if start > data.len() {
+        return Err(ReaderError::UnknownFormat);
+    }
+
+    // Undo the substitution pass, then the additive pass that followed it.
+    for byte in &mut data[start..] {
+        *byte = reverse[*byte as usize];
+    }
+    let mut carry = 0u8;
+    for byte in &mut data[start..] {
+        let plain = byte.wrapping_sub(carry);
+        carry = *byte;
+        *byte = plain;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{reverse_table, swap_table};
+
+    #[test]
+    fn swap_table_is_a_permutation() {
+        // Whatever the seed, the schedule only ever swaps adjacent slots of the
+        // identity, so every value 0..=255 must appear exactly once.
+        for seed in [0u32, 1, 42, 1_234_321, 0xDEAD_BEEF] {
+            let table = swap_table(seed);
+            let mut seen = [false; 256];
+            for &v in &table {
+                assert!(!seen[v as usize], "value {} repeated for seed {}", v, seed);
+                seen[v as usize] = true;
+            }
+        }
+    }
+
+    #[test]
+    fn reverse_table_inverts_swap_table() {
+        let table = swap_table(1_234_321);
+        let reverse = reverse_table(&table);
+        for i in 0..256usize {
+            assert_eq!(reverse[table[i] as usize] as usize, i);
+        }
+    }
+}
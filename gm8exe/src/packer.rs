@@ -0,0 +1,133 @@
+mod aplib;
+
+use crate::byteio::ByteReader;
+use crate::{reader::ReaderError, upx};
+
+/// Optional progress logger, as a trait object so [`Packer`] stays object-safe.
+type Logger<'a> = Option<&'a dyn Fn(&str)>;
+
+/// One entry of the PE section table, as much of it as the packer stage needs.
+///
+/// The reader builds these while parsing the PE header and hands the slice to
+/// [`gamedata::find`](crate::gamedata::find); keeping the packers' view this
+/// small means they don't depend on the rest of the PE parser.
+pub struct PeSection {
+    /// Section name, trimmed of its trailing NUL padding (e.g. `"UPX1"`).
+    pub name: String,
+    /// Virtual size of the section once mapped into memory.
+    pub virtual_size: u32,
+    /// File offset of the section's raw data on disk.
+    pub disk_offset: u32,
+    /// Size of the section's raw data on disk.
+    pub disk_size: u32,
+}
+
+/// What a [`Packer`] extracts from the section table and needs to decompress.
+pub struct PackerParams {
+    /// Upper bound on the decompressed size, used to pre-size the output buffer.
+    pub max_size: u32,
+    /// File offset of the packed payload on disk.
+    pub disk_offset: u32,
+}
+
+/// A recogniser and decompressor for one executable packer.
+///
+/// [`find`](crate::gamedata::find) keeps an ordered registry of these; the first
+/// whose [`detect`](Packer::detect) matches the section table is asked to
+/// [`unpack`](Packer::unpack) the image, after which format detection re-runs on
+/// the decompressed buffer. `find` only consults [`registry`]; a packer
+/// implemented outside this module is not added to it.
+pub trait Packer {
+    /// Human-readable packer name, used in log output.
+    fn name(&self) -> &'static str;
+
+    /// Inspect the section table, returning the unpack parameters if this packer
+    /// is in use or `None` otherwise.
+    fn detect(&self, sections: &[PeSection]) -> Option<PackerParams>;
+
+    /// Decompress the packed image into a fresh buffer.
+    fn unpack(
+        &self,
+        exe: &mut dyn ByteReader,
+        params: &PackerParams,
+        logger: Logger,
+    ) -> Result<Vec<u8>, ReaderError>;
+}
+
+/// The built-in packer cascade, tried in order by `find`.
+pub fn registry() -> Vec<Box<dyn Packer>> {
+    vec![Box::new(Upx), Box::new(Aplib)]
+}
+
+/// UPX, the most common wrapper seen on GM8 executables.
+struct Upx;
+
+impl Packer for Upx {
+    fn name(&self) -> &'static str {
+        "UPX"
+    }
+
+    fn detect(&self, sections: &[PeSection]) -> Option<PackerParams> {
+        let upx0 = sections.iter().find(|s| s.name == "UPX0")?;
+        let upx1 = sections.iter().find(|s| s.name == "UPX1")?;
+        // A malformed section table can claim sizes that overflow `u32`; treat
+        // that as "not UPX" rather than panicking on attacker-controlled headers.
+        Some(PackerParams {
+            max_size: upx0.virtual_size.checked_add(upx1.virtual_size)?,
+            disk_offset: upx1.disk_offset,
+        })
+    }
+
+    fn unpack(
+        &self,
+        exe: &mut dyn ByteReader,
+        params: &PackerParams,
+        logger: Logger,
+    ) -> Result<Vec<u8>, ReaderError> {
+        upx::unpack(exe, params.max_size, params.disk_offset, logger)
+    }
+}
+
+/// A protector that stores its payload as a single contiguous aPLib stream in a
+/// section named `.aplib`.
+///
+/// Unlike ASPack (which ships its own multi-stage LZ stub, not aPLib), these
+/// custom GM8 protectors drop the aPACK-compressed original image straight at
+/// the section's raw offset, so [`aplib::depack`] reconstructs it directly.
+struct Aplib;
+
+impl Packer for Aplib {
+    fn name(&self) -> &'static str {
+        "aPLib"
+    }
+
+    fn detect(&self, sections: &[PeSection]) -> Option<PackerParams> {
+        let aplib = sections.iter().find(|s| s.name == ".aplib")?;
+        // The unpacked image spans every section's virtual size, so that sum
+        // bounds the decompressed output. A malformed section table can overflow
+        // `u32`; treat that as "not this packer" rather than panicking.
+        let max_size = sections
+            .iter()
+            .try_fold(0u32, |acc, s| acc.checked_add(s.virtual_size))?;
+        Some(PackerParams {
+            max_size,
+            disk_offset: aplib.disk_offset,
+        })
+    }
+
+    fn unpack(
+        &self,
+        exe: &mut dyn ByteReader,
+        params: &PackerParams,
+        logger: Logger,
+    ) -> Result<Vec<u8>, ReaderError> {
+        log!(
+            logger,
+            "Decompressing aPLib stream at 0x{:X}",
+            params.disk_offset
+        );
+        let start = params.disk_offset as usize;
+        let packed = exe.as_bytes().get(start..).ok_or(ReaderError::UnknownFormat)?;
+        aplib::depack(packed, params.max_size as usize)
+    }
+}
@@ -0,0 +1,159 @@
+//! A small, self-contained aPLib depacker.
+//!
+//! A number of custom GM8 protectors compress their payload with aPLib, so the
+//! aPLib [`Packer`](super::Packer) needs a decompressor. This is a
+//! direct port of the canonical `aP_depack` reference algorithm, reading the
+//! bitstream MSB-first and reconstructing the output one token at a time.
+
+use crate::reader::ReaderError;
+
+/// MSB-first bit reader over the packed byte stream.
+struct BitReader<'a> {
+    src: &'a [u8],
+    pos: usize,
+    tag: u8,
+    bits_left: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(src: &'a [u8]) -> Self {
+        BitReader {
+            src,
+            pos: 0,
+            tag: 0,
+            bits_left: 0,
+        }
+    }
+
+    /// Read the next raw byte from the stream.
+    fn read_byte(&mut self) -> Result<u8, ReaderError> {
+        let byte = *self.src.get(self.pos).ok_or(ReaderError::UnknownFormat)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    /// Read a single bit, refilling the tag byte when exhausted.
+    fn read_bit(&mut self) -> Result<u32, ReaderError> {
+        if self.bits_left == 0 {
+            self.tag = self.read_byte()?;
+            self.bits_left = 8;
+        }
+        let bit = (self.tag >> 7) & 1;
+        self.tag <<= 1;
+        self.bits_left -= 1;
+        Ok(bit as u32)
+    }
+
+    /// Read an interlaced gamma2-coded integer.
+    fn read_gamma(&mut self) -> Result<u32, ReaderError> {
+        let mut result = 1u32;
+        loop {
+            result = (result << 1) + self.read_bit()?;
+            if self.read_bit()? == 0 {
+                break;
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Copy `len` bytes from `offset` back in `out`, honouring overlap (RLE-style).
+fn copy_match(out: &mut Vec<u8>, offset: usize, len: usize) -> Result<(), ReaderError> {
+    if offset == 0 || offset > out.len() {
+        return Err(ReaderError::UnknownFormat);
+    }
+    for _ in 0..len {
+        let byte = out[out.len() - offset];
+        out.push(byte);
+    }
+    Ok(())
+}
+
+/// Decompress an aPLib stream, pre-allocating `expected_size` bytes of output.
+pub fn depack(src: &[u8], expected_size: usize) -> Result<Vec<u8>, ReaderError> {
+    let mut reader = BitReader::new(src);
+    let mut out: Vec<u8> = Vec::with_capacity(expected_size);
+
+    // The first byte is always emitted verbatim.
+    out.push(reader.read_byte()?);
+
+    let mut last_offset = 0usize;
+    let mut lwm = 0u32;
+
+    loop {
+        if reader.read_bit()? == 1 {
+            if reader.read_bit()? == 1 {
+                if reader.read_bit()? == 1 {
+                    // 111: short 4-bit back-reference (or a literal zero).
+                    let mut offset = 0usize;
+                    for _ in 0..4 {
+                        offset = (offset << 1) + reader.read_bit()? as usize;
+                    }
+                    if offset == 0 {
+                        out.push(0x00);
+                    } else {
+                        copy_match(&mut out, offset, 1)?;
+                    }
+                    lwm = 0;
+                } else {
+                    // 110: single-byte offset, length encoded in its low bit.
+                    let byte = reader.read_byte()? as usize;
+                    let len = 2 + (byte & 1);
+                    let offset = byte >> 1;
+                    if offset == 0 {
+                        // End-of-stream marker.
+                        break;
+                    }
+                    copy_match(&mut out, offset, len)?;
+                    last_offset = offset;
+                    lwm = 1;
+                }
+            } else {
+                // 10: gamma-coded offset and length.
+                let mut offset = reader.read_gamma()? as usize;
+                if lwm == 0 && offset == 2 {
+                    // Reuse the previous offset with a fresh length.
+                    let len = reader.read_gamma()? as usize;
+                    copy_match(&mut out, last_offset, len)?;
+                } else {
+                    offset -= if lwm == 0 { 3 } else { 2 };
+                    offset = (offset << 8) + reader.read_byte()? as usize;
+                    let mut len = reader.read_gamma()? as usize;
+                    if offset >= 32000 {
+                        len += 1;
+                    }
+                    if offset >= 1280 {
+                        len += 1;
+                    }
+                    if offset < 128 {
+                        len += 2;
+                    }
+                    copy_match(&mut out, offset, len)?;
+                    last_offset = offset;
+                }
+                lwm = 1;
+            }
+        } else {
+            // 0: verbatim literal byte.
+            out.push(reader.read_byte()?);
+            lwm = 0;
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::depack;
+
+    #[test]
+    fn depack_literal_stream() {
+        // Hand-assembled aPLib stream exercising the verbatim-literal and
+        // end-of-stream paths: first byte 'A' is emitted raw, the tag byte
+        // 0b0011_0000 drives two literal bits then the `110` end marker, and the
+        // trailing 0x00 offset terminates the stream.
+        let packed = [b'A', 0b0011_0000, b'B', b'C', 0x00];
+        assert_eq!(depack(&packed, 3).unwrap(), b"ABC");
+    }
+}
@@ -0,0 +1,214 @@
+//! Post-decryption integrity verification and game identification.
+//!
+//! antidec and gm81 decryption can silently produce garbage if the masks or XOR
+//! method were read wrong. Once a [`GameVersion`](crate::GameVersion) is
+//! resolved, the recovered gamedata region is hashed (CRC32 and SHA-1) and
+//! looked up in an optional user-supplied [`ChecksumDatabase`]; a hit both
+//! confirms the decryption succeeded and names the exact game. The check is
+//! skipped entirely when no database is supplied.
+//!
+//! The hashing and lookup are `no_std`; only [`ChecksumDatabase::from_file`]
+//! needs `std` and is gated behind the `std` feature, so the verification core
+//! still builds for `wasm32-unknown-unknown`.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+#[cfg(feature = "std")]
+use std::fs::File;
+#[cfg(feature = "std")]
+use std::io::{self, BufRead, BufReader};
+#[cfg(feature = "std")]
+use std::path::Path;
+
+/// The game a recovered gamedata region was identified as.
+#[derive(Clone, Debug)]
+pub struct GameId {
+    /// Title of the game, e.g. `"Spelunky"`.
+    pub name: String,
+    /// Free-form version string, e.g. `"1.1"`.
+    pub version: String,
+}
+
+/// A single database row within its CRC32 bucket: the SHA-1 plus the game it
+/// identifies.
+struct Entry {
+    sha1: [u8; 20],
+    id: GameId,
+}
+
+/// A redump-style table mapping gamedata hashes to known games.
+///
+/// Rows are bucketed by CRC32; SHA-1 disambiguates collisions. Load one with
+/// [`from_file`](ChecksumDatabase::from_file) and query it with
+/// [`identify`](ChecksumDatabase::identify).
+#[derive(Default)]
+pub struct ChecksumDatabase {
+    entries: BTreeMap<u32, Vec<Entry>>,
+}
+
+impl ChecksumDatabase {
+    /// Load a database from a simple tab-separated text file. Each line is
+    /// `<crc32-hex>\t<sha1-hex>\t<name>\t<version>`; blank lines and lines
+    /// starting with `#` are ignored.
+    #[cfg(feature = "std")]
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut db = ChecksumDatabase::default();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.splitn(4, '\t');
+            let crc = fields
+                .next()
+                .and_then(|s| u32::from_str_radix(s.trim(), 16).ok());
+            let sha1 = fields.next().and_then(|s| parse_sha1(s.trim()));
+            let name = fields.next().map(str::to_owned);
+            let version = fields.next().map(str::to_owned);
+            match (crc, sha1, name, version) {
+                (Some(crc), Some(sha1), Some(name), Some(version)) => {
+                    db.entries
+                        .entry(crc)
+                        .or_default()
+                        .push(Entry { sha1, id: GameId { name, version } });
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("malformed checksum database row: {}", line),
+                    ))
+                }
+            }
+        }
+        Ok(db)
+    }
+
+    /// Hash `gamedata` and return the matching game, or `None` if no row matches.
+    pub fn identify(&self, gamedata: &[u8]) -> Option<GameId> {
+        let bucket = self.entries.get(&crc32(gamedata))?;
+        let sha1 = sha1(gamedata);
+        bucket
+            .iter()
+            .find(|entry| entry.sha1 == sha1)
+            .map(|entry| entry.id.clone())
+    }
+}
+
+/// Parse a 40-character hex SHA-1 digest.
+fn parse_sha1(s: &str) -> Option<[u8; 20]> {
+    if s.len() != 40 {
+        return None;
+    }
+    let mut out = [0u8; 20];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(out)
+}
+
+/// Compute the CRC32 (IEEE, reflected) of `data`.
+fn crc32(data: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (i, slot) in table.iter_mut().enumerate() {
+        let mut c = i as u32;
+        for _ in 0..8 {
+            c = if c & 1 != 0 {
+                0xEDB8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+        }
+        *slot = c;
+    }
+    let mut crc = 0xFFFF_FFFFu32;
+    for &b in data {
+        crc = table[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    crc ^ 0xFFFF_FFFF
+}
+
+/// Compute the SHA-1 digest of `data`.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476, 0xC3D2_E1F0];
+    let bit_len = (data.len() as u64).wrapping_mul(8);
+
+    let mut msg = data.to_vec();
+    msg.push(0x80);
+    while msg.len() % 64 != 56 {
+        msg.push(0);
+    }
+    msg.extend_from_slice(&bit_len.to_be_bytes());
+
+    for chunk in msg.chunks_exact(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in w.iter_mut().take(16).enumerate() {
+            *word = u32::from_be_bytes([
+                chunk[i * 4],
+                chunk[i * 4 + 1],
+                chunk[i * 4 + 2],
+                chunk[i * 4 + 3],
+            ]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let (mut a, mut b, mut c, mut d, mut e) = (h[0], h[1], h[2], h[3], h[4]);
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | ((!b) & d), 0x5A82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+            let tmp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = tmp;
+        }
+        h[0] = h[0].wrapping_add(a);
+        h[1] = h[1].wrapping_add(b);
+        h[2] = h[2].wrapping_add(c);
+        h[3] = h[3].wrapping_add(d);
+        h[4] = h[4].wrapping_add(e);
+    }
+
+    let mut out = [0u8; 20];
+    for (i, word) in h.iter().enumerate() {
+        out[i * 4..i * 4 + 4].copy_from_slice(&word.to_be_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, parse_sha1, sha1};
+
+    #[test]
+    fn crc32_known_answers() {
+        assert_eq!(crc32(b""), 0x0000_0000);
+        assert_eq!(crc32(b"abc"), 0x3524_41C2);
+    }
+
+    #[test]
+    fn sha1_known_answers() {
+        assert_eq!(
+            sha1(b""),
+            parse_sha1("da39a3ee5e6b4b0d3255bfef95601890afd80709").unwrap()
+        );
+        assert_eq!(
+            sha1(b"abc"),
+            parse_sha1("a9993e364706816aba3e25717850c26c9cd0d89d").unwrap()
+        );
+    }
+}